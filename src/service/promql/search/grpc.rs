@@ -0,0 +1,53 @@
+// Copyright 2024 OpenObserve Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Node-side (querier) half of two cross-node contracts the coordinator in
+//! `super` relies on: response checksums and per-sample merge metadata.
+//!
+//! The gRPC query handler that actually executes a sub-query on this node,
+//! reads samples off WAL/storage, and assembles the `MetricsQueryResponse`
+//! isn't part of this checkout, and neither is the `cluster_rpc` proto
+//! change (`checksum` on `MetricsQueryResponse`, `stamp`/`source`/`node_id`
+//! on `Sample`) these hooks assume. Nothing calls [`stamp_sample`] or
+//! [`stamp_checksum`] anywhere in this checkout -- they're the node-side
+//! half of the contract `search_in_cluster`'s
+//! `cfg.limit.metrics_verify_response_checksums` check and
+//! `super::merge_tiebreak_rank` are written against (the former off by
+//! default, the latter degraded to an arbitrary tie-break, for exactly this
+//! reason), so that handler has a named place to call into once it and the
+//! proto change land together.
+
+use config::utils::time::now_micros;
+use proto::cluster_rpc;
+
+use super::series_checksum;
+
+/// Stamp `sample` with a version stamp, which tier it was read from, and
+/// `node_id`. The stamp is the current time (`now_micros()`) at the moment of
+/// this call, not the time the sample was ingested. Call this wherever a node reads a sample off WAL or storage, before
+/// it's placed into a `MetricsQueryResponse` -- once wired in, this is what
+/// would let `super::merge_tiebreak_rank` implement real last-write-wins
+/// semantics instead of its current value-bits tie-break.
+pub fn stamp_sample(sample: &mut cluster_rpc::Sample, source: cluster_rpc::SampleSource, node_id: u64) {
+    sample.stamp = now_micros();
+    sample.source = source as i32;
+    sample.node_id = node_id;
+}
+
+/// Set `response.checksum` to the hash of `response.result`. Call this as
+/// the last step before a querier node returns a `MetricsQueryResponse`.
+pub fn stamp_checksum(response: &mut cluster_rpc::MetricsQueryResponse) {
+    response.checksum = series_checksum(&response.result);
+}
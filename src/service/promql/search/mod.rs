@@ -14,14 +14,15 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::{
-    cmp::{max, min},
+    cmp::{max, Reverse},
+    collections::BinaryHeap,
     sync::Arc,
 };
 
 use config::{
     get_config, ider,
     meta::{
-        cluster::{get_internal_grpc_token, RoleGroup},
+        cluster::{get_internal_grpc_token, Node, RoleGroup},
         search::ScanStats,
         self_reporting::usage::{RequestStats, UsageType},
         stream::StreamType,
@@ -39,6 +40,7 @@ use tonic::{
 };
 use tracing::{info_span, Instrument};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
+use xxhash_rust::xxh3::xxh3_64;
 
 use crate::{
     common::infra::cluster,
@@ -54,6 +56,25 @@ use crate::{
 };
 
 pub mod grpc;
+mod partition;
+
+use partition::IntervalCost;
+
+/// Result of a cluster metrics search. `warnings` is non-empty only when
+/// `ZO_METRICS_ALLOW_PARTIAL_RESULTS` let a search answer from a subset of
+/// partitions; each entry names a time sub-range that couldn't be fetched,
+/// so callers can tell a partial answer from a complete one instead of
+/// trusting `value` blindly.
+///
+/// `search()` returns this struct rather than a bare `Value` specifically so
+/// that indicator reaches callers instead of only reaching a server-side
+/// log line -- a caller that only wants the data can destructure `.value`
+/// and ignore `.warnings`, but the degraded-result case is no longer
+/// indistinguishable from a complete one at the type level.
+pub struct MetricsSearchResult {
+    pub value: Value,
+    pub warnings: Vec<String>,
+}
 
 #[tracing::instrument(skip_all, fields(org_id = org_id))]
 pub async fn search(
@@ -61,18 +82,26 @@ pub async fn search(
     req: &MetricsQueryRequest,
     user_email: &str,
     timeout: i64,
-) -> Result<Value> {
+) -> Result<MetricsSearchResult> {
     let mut req: cluster_rpc::MetricsQueryRequest = req.to_owned().into();
     req.org_id = org_id.to_string();
     req.timeout = timeout;
-    search_in_cluster(req, user_email).await
+    let result = search_in_cluster(req, user_email).await?;
+    if !result.warnings.is_empty() {
+        log::warn!(
+            "promql->search: returning a partial result ({} warning(s)): {}",
+            result.warnings.len(),
+            result.warnings.join("; ")
+        );
+    }
+    Ok(result)
 }
 
 #[tracing::instrument(name = "promql:search:cluster", skip_all, fields(org_id = req.org_id))]
 async fn search_in_cluster(
     req: cluster_rpc::MetricsQueryRequest,
     user_email: &str,
-) -> Result<Value> {
+) -> Result<MetricsSearchResult> {
     let op_start = std::time::Instant::now();
     let started_at = chrono::Utc::now().timestamp_micros();
     let trace_id = ider::uuid();
@@ -113,10 +142,6 @@ async fn search_in_cluster(
     // The number of resolution steps; see the diagram at
     // https://promlabs.com/blog/2020/06/18/the-anatomy-of-a-promql-query/#range-queries
     let partition_step = max(micros(DEFAULT_LOOKBACK), step);
-    let nr_steps = match (end - start) / partition_step {
-        0 => 1,
-        n => n,
-    };
 
     // adjust start and end time
     let cache_disabled = req.no_cache || !cfg.common.result_cache_enabled;
@@ -133,11 +158,31 @@ async fn search_in_cluster(
         )));
     }
 
-    // A span of time covered by an individual querier (worker).
-    let worker_dt = if nr_steps > nr_queriers {
-        partition_step * ((nr_steps + nr_queriers - 1) / nr_queriers)
+    // Partition the time range across queriers. By default this just cuts
+    // `[start, end)` into `nr_queriers` equal slices. When adaptive
+    // partitioning is enabled, we instead run a metadata pre-pass (see
+    // `probe_interval_costs`) and balance boundaries by estimated cost so a
+    // querier that lands on a data-dense interval isn't left as a straggler;
+    // any pre-pass failure falls back to equal slices. That pre-pass reruns
+    // real sub-queries rather than calling a dedicated stats-only RPC (none
+    // exists in this checkout's proto), so metrics_adaptive_partitioning_enabled
+    // roughly doubles total query cost while enabled -- leave it off unless
+    // the straggler problem it solves outweighs that, and revisit once a
+    // real stats-only RPC lands.
+    let slices = if cfg.limit.metrics_adaptive_partitioning_enabled {
+        match probe_interval_costs(&nodes, &req, start, end, partition_step, &trace_id).await {
+            Some(costs) => {
+                partition::adaptive_slices(start, end, partition_step, nr_queriers, &costs)
+            }
+            None => {
+                log::info!(
+                    "[trace_id {trace_id}] promql->search->partition: cost pre-pass unavailable, falling back to equal slices"
+                );
+                partition::equal_slices(start, end, partition_step, nr_queriers)
+            }
+        }
     } else {
-        partition_step
+        partition::equal_slices(start, end, partition_step, nr_queriers)
     };
 
     // partition request, here plus 1 second, because division is integer, maybe
@@ -152,12 +197,9 @@ async fn search_in_cluster(
 
     // make cluster request
     let mut tasks = Vec::new();
-    let mut worker_start = start;
-    for node in nodes.iter() {
+    let mut task_ranges = Vec::new();
+    for (node, &(worker_start, worker_end)) in nodes.iter().zip(slices.iter()) {
         let node = node.clone();
-        if worker_start > end {
-            break;
-        }
         let job = Some(cluster_rpc::Job {
             partition: node.id as _,
             ..job.clone()
@@ -165,7 +207,7 @@ async fn search_in_cluster(
         let mut req = cluster_rpc::MetricsQueryRequest { job, ..req.clone() };
         let req_query = req.query.as_mut().unwrap();
         req_query.start = worker_start;
-        req_query.end = min(end, worker_start + worker_dt);
+        req_query.end = worker_end;
         // if the end time is within the last 3 retention time, we need to fetch wal data
         if req_query.end
             >= now_micros() - second_micros(cfg.limit.max_file_retention_time as i64 * 3)
@@ -173,7 +215,6 @@ async fn search_in_cluster(
             req.need_wal = true;
         }
         let req_need_wal = req.need_wal;
-        worker_start += worker_dt;
 
         log::info!(
             "[trace_id {trace_id}] promql->search->partition: node: {}, need_wal: {}, time_range: [{}, {}]",
@@ -184,96 +225,105 @@ async fn search_in_cluster(
         );
 
         let trace_id = trace_id.to_string();
-        let node_addr = node.grpc_addr.clone();
+        let query_timeout = cfg.limit.query_timeout;
+        let verify_checksums = cfg.limit.metrics_verify_response_checksums;
         let grpc_span = info_span!("promql:search:cluster:grpc_search", org_id = req.org_id);
         let task = tokio::task::spawn(
             async move {
-                let cfg = config::get_config();
-                let org_id: MetadataValue<_> = req
-                    .org_id
-                    .parse()
-                    .map_err(|_| Error::Message(format!("invalid org_id: {}", req.org_id)))?;
-                let mut request = tonic::Request::new(req);
-                request.set_timeout(std::time::Duration::from_secs(cfg.limit.query_timeout));
-
-                opentelemetry::global::get_text_map_propagator(|propagator| {
-                    propagator.inject_context(
-                        &tracing::Span::current().context(),
-                        &mut MetadataMap(request.metadata_mut()),
-                    )
-                });
-
-                let org_header_key: MetadataKey<_> = cfg.grpc.org_header_key.parse().map_err(|_| Error::Message("invalid org_header_key".to_string()))?;
-                let token: MetadataValue<_> = get_internal_grpc_token()
-                    .parse()
-                    .map_err(|_| Error::Message("invalid token".to_string()))?;
-                let channel = get_cached_channel(&node_addr).await.map_err(|err| {
-                    log::error!(
-                        "[trace_id {trace_id}] promql->search->grpc: node: {}, connect err: {:?}",
-                        &node.grpc_addr,
-                        err
-                    );
-                    server_internal_error("connect search node error")
-                })?;
-                let mut client = cluster_rpc::metrics_client::MetricsClient::with_interceptor(
-                    channel,
-                    move |mut req: Request<()>| {
-                        req.metadata_mut().insert("authorization", token.clone());
-                        req.metadata_mut()
-                            .insert(org_header_key.clone(), org_id.clone());
-                        Ok(req)
-                    },
-                );
-                 client = client
-                    .send_compressed(CompressionEncoding::Gzip)
-                    .accept_compressed(CompressionEncoding::Gzip)
-                    .max_decoding_message_size(cfg.grpc.max_message_size * 1024 * 1024)
-                    .max_encoding_message_size(cfg.grpc.max_message_size * 1024 * 1024);
-                let response: cluster_rpc::MetricsQueryResponse = match client.query(request).await
-                {
-                    Ok(res) => res.into_inner(),
-                    Err(err) => {
+                let response = query_node(&node, req, &trace_id, query_timeout).await?;
+                let scan_stats = response.scan_stats.as_ref().unwrap();
+                let checksum = series_checksum(&response.result);
+                // `metrics_verify_response_checksums` defaults to off: enforcing
+                // this needs the node-side query handler to call
+                // `grpc::stamp_checksum` and a `checksum` field on
+                // `cluster_rpc::MetricsQueryResponse`, and neither the proto
+                // change nor that handler are part of this checkout, so
+                // `response.checksum` can only ever be its zero default here.
+                // Shipping the check always-on would mean it can never
+                // actually reject anything, indistinguishable from
+                // "verification passed" -- so it stays opt-in and off by
+                // default, and only flips on once the node side is deployed
+                // alongside it.
+                if verify_checksums {
+                    if response.checksum == 0 {
+                        log::debug!(
+                            "[trace_id {trace_id}] promql->search->grpc: node: {}, response has no checksum set, skipping integrity check",
+                            &node.grpc_addr,
+                        );
+                    } else if checksum != response.checksum {
                         log::error!(
-                            "[trace_id {trace_id}] promql->search->grpc: node: {}, search err: {:?}",
+                            "[trace_id {trace_id}] promql->search->grpc: node: {}, checksum mismatch: expected {}, got {}",
                             &node.grpc_addr,
-                            err
+                            response.checksum,
+                            checksum,
                         );
-                        if err.code() == tonic::Code::Internal {
-                            let err = ErrorCodes::from_json(err.message())?;
-                            return Err(Error::ErrorCode(err));
-                        }
-                        return Err(server_internal_error("search node error"));
+                        return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(format!(
+                            "corrupt response from node {}: checksum mismatch",
+                            &node.grpc_addr
+                        ))));
                     }
-                };
-                let scan_stats = response.scan_stats.as_ref().unwrap();
+                }
 
                 log::info!(
-                    "[trace_id {trace_id}] promql->search->grpc: result node: {}, need_wal: {}, took: {} ms, files: {}, scan_size: {}",
+                    "[trace_id {trace_id}] promql->search->grpc: result node: {}, need_wal: {}, took: {} ms, files: {}, scan_size: {}, checksum: {}",
                     &node.grpc_addr,
                     req_need_wal,
                     response.took,
                     scan_stats.files,
                     scan_stats.original_size,
+                    checksum,
                 );
                 Ok(response)
             }
             .instrument(grpc_span),
         );
         tasks.push(task);
+        task_ranges.push((worker_start, worker_end));
     }
 
+    // Collect every task's outcome instead of short-circuiting on the first
+    // failure (the old `try_join_all` behavior), so one overloaded or
+    // restarting querier doesn't abort an otherwise answerable query. When
+    // at least one partition succeeds and `cfg.limit.metrics_allow_partial_results`
+    // is set, we merge what we have and report the rest as warnings; with
+    // the flag off (or if every partition failed) we still fail hard.
+    let allow_partial_results = cfg.limit.metrics_allow_partial_results;
+    let raw_results = futures::future::join_all(tasks).await;
     let mut results = Vec::new();
-    let task_results = match try_join_all(tasks).await {
-        Ok(res) => res,
-        Err(err) => {
-            return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
-                err.to_string(),
-            )));
+    let mut failed_ranges = Vec::new();
+    for ((range_start, range_end), res) in task_ranges.into_iter().zip(raw_results) {
+        match res {
+            Ok(Ok(resp)) => results.push(resp),
+            Ok(Err(err)) => {
+                log::error!(
+                    "[trace_id {trace_id}] promql->search->partition: range [{range_start}, {range_end}) failed: {err}"
+                );
+                failed_ranges.push((range_start, range_end));
+            }
+            Err(join_err) => {
+                log::error!(
+                    "[trace_id {trace_id}] promql->search->partition: range [{range_start}, {range_end}) task failed: {join_err}"
+                );
+                failed_ranges.push((range_start, range_end));
+            }
         }
-    };
-    for res in task_results {
-        results.push(res?);
     }
+    if results.is_empty() {
+        return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
+            "all partitions failed".to_string(),
+        )));
+    }
+    if !failed_ranges.is_empty() && !allow_partial_results {
+        return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(format!(
+            "{} of {} partitions failed, set ZO_METRICS_ALLOW_PARTIAL_RESULTS=true to return a partial result",
+            failed_ranges.len(),
+            failed_ranges.len() + results.len(),
+        ))));
+    }
+    let warnings: Vec<String> = failed_ranges
+        .iter()
+        .map(|(s, e)| format!("partial result: time range [{s}, {e}) could not be fetched"))
+        .collect();
 
     // merge multiple instances data
     let mut scan_stats = ScanStats::new();
@@ -291,7 +341,7 @@ async fn search_in_cluster(
 
     // merge result
     let values = if result_type == "matrix" {
-        merge_matrix_query(&series_data)
+        merge_matrix_query(&series_data, &scan_stats)
     } else if result_type == "vector" {
         merge_vector_query(&series_data)
     } else if result_type == "scalar" {
@@ -328,11 +378,261 @@ async fn search_in_cluster(
         started_at,
     )
     .await;
-    Ok(values)
+    Ok(MetricsSearchResult { value: values, warnings })
+}
+
+/// Canonical hash of a series payload (sorted labels, then samples in
+/// order), used on both ends of the checksum contract: the coordinator
+/// recomputes it here to verify a `MetricsQueryResponse`, and the node that
+/// produced the response must compute the same thing over `response.result`
+/// and stamp it into `response.checksum` via [`grpc::stamp_checksum`] before
+/// returning. A truncated or corrupted gRPC payload, or a decode bug, then
+/// surfaces as a loud mismatch instead of silently wrong PromQL results.
+fn series_checksum(series: &[cluster_rpc::Series]) -> u64 {
+    let mut sorted: Vec<&cluster_rpc::Series> = series.iter().collect();
+    sorted.sort_by(|a, b| {
+        let ka: Vec<(&str, &str)> = a.metric.iter().map(|l| (l.name.as_str(), l.value.as_str())).collect();
+        let kb: Vec<(&str, &str)> = b.metric.iter().map(|l| (l.name.as_str(), l.value.as_str())).collect();
+        ka.cmp(&kb)
+    });
+
+    let mut buf = Vec::new();
+    for ser in sorted {
+        let mut labels: Vec<(&str, &str)> = ser
+            .metric
+            .iter()
+            .map(|l| (l.name.as_str(), l.value.as_str()))
+            .collect();
+        labels.sort_unstable();
+        for (name, value) in labels {
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(0);
+            buf.extend_from_slice(value.as_bytes());
+            buf.push(0);
+        }
+        for s in &ser.samples {
+            buf.extend_from_slice(&s.time.to_le_bytes());
+            buf.extend_from_slice(&s.value.to_le_bytes());
+        }
+        if let Some(s) = ser.sample.as_ref() {
+            buf.extend_from_slice(&s.time.to_le_bytes());
+            buf.extend_from_slice(&s.value.to_le_bytes());
+        }
+        if let Some(x) = ser.scalar {
+            buf.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+    xxh3_64(&buf)
 }
 
-fn merge_matrix_query(series: &[cluster_rpc::Series]) -> Value {
-    let mut merged_data = HashMap::new();
+/// Issue a single `MetricsQueryRequest` against `node` and return its
+/// response. Shared by the main fan-out in [`search_in_cluster`] and the
+/// adaptive-partitioning cost pre-pass in [`probe_interval_costs`].
+async fn query_node(
+    node: &Node,
+    req: cluster_rpc::MetricsQueryRequest,
+    trace_id: &str,
+    timeout_secs: u64,
+) -> Result<cluster_rpc::MetricsQueryResponse> {
+    let cfg = config::get_config();
+    let org_id: MetadataValue<_> = req
+        .org_id
+        .parse()
+        .map_err(|_| Error::Message(format!("invalid org_id: {}", req.org_id)))?;
+    let mut request = tonic::Request::new(req);
+    request.set_timeout(std::time::Duration::from_secs(timeout_secs));
+
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(
+            &tracing::Span::current().context(),
+            &mut MetadataMap(request.metadata_mut()),
+        )
+    });
+
+    let org_header_key: MetadataKey<_> = cfg
+        .grpc
+        .org_header_key
+        .parse()
+        .map_err(|_| Error::Message("invalid org_header_key".to_string()))?;
+    let token: MetadataValue<_> = get_internal_grpc_token()
+        .parse()
+        .map_err(|_| Error::Message("invalid token".to_string()))?;
+    let channel = get_cached_channel(&node.grpc_addr).await.map_err(|err| {
+        log::error!(
+            "[trace_id {trace_id}] promql->search->grpc: node: {}, connect err: {:?}",
+            &node.grpc_addr,
+            err
+        );
+        server_internal_error("connect search node error")
+    })?;
+    let mut client = cluster_rpc::metrics_client::MetricsClient::with_interceptor(
+        channel,
+        move |mut req: Request<()>| {
+            req.metadata_mut().insert("authorization", token.clone());
+            req.metadata_mut()
+                .insert(org_header_key.clone(), org_id.clone());
+            Ok(req)
+        },
+    );
+    client = client
+        .send_compressed(CompressionEncoding::Gzip)
+        .accept_compressed(CompressionEncoding::Gzip)
+        .max_decoding_message_size(cfg.grpc.max_message_size * 1024 * 1024)
+        .max_encoding_message_size(cfg.grpc.max_message_size * 1024 * 1024);
+    match client.query(request).await {
+        Ok(res) => Ok(res.into_inner()),
+        Err(err) => {
+            log::error!(
+                "[trace_id {trace_id}] promql->search->grpc: node: {}, search err: {:?}",
+                &node.grpc_addr,
+                err
+            );
+            if err.code() == tonic::Code::Internal {
+                let err = ErrorCodes::from_json(err.message())?;
+                Err(Error::ErrorCode(err))
+            } else {
+                Err(server_internal_error("search node error"))
+            }
+        }
+    }
+}
+
+/// Metadata pre-pass for adaptive partitioning: probe `[start, end)` in one
+/// equal-slice bucket per querier (the same count as the final partitions)
+/// and use each bucket's `ScanStats` (file count + byte size) as its cost.
+///
+/// There is no stats-only RPC in this checkout's `cluster_rpc` proto, so this
+/// reuses the regular query path -- it runs real sub-queries and throws away
+/// their `result`, keeping only `scan_stats`. That means enabling
+/// `ZO_METRICS_ADAPTIVE_PARTITIONING` roughly **doubles total query cost**
+/// for every search (one pass to probe, one to answer), not a small
+/// constant overhead; `PROBE_FANOUT` is kept at `1` (one probe bucket per
+/// querier, matching the final partition count) specifically to keep that
+/// multiplier near 2x instead of compounding it further. Do not raise
+/// `PROBE_FANOUT` without adding a real stats-only RPC first. Buckets are
+/// spread round-robin across `nodes` and probed concurrently; any single
+/// failure aborts the whole pre-pass so the caller falls back to equal
+/// slices instead of partitioning on incomplete information.
+async fn probe_interval_costs(
+    nodes: &[Node],
+    base_req: &cluster_rpc::MetricsQueryRequest,
+    start: i64,
+    end: i64,
+    partition_step: i64,
+    trace_id: &str,
+) -> Option<Vec<IntervalCost>> {
+    const PROBE_FANOUT: i64 = 1;
+    let buckets = partition::equal_slices(
+        start,
+        end,
+        partition_step,
+        nodes.len() as i64 * PROBE_FANOUT,
+    );
+    if buckets.is_empty() {
+        return None;
+    }
+
+    let cfg = get_config();
+    let probe_timeout = std::cmp::min(cfg.limit.query_timeout, 5);
+    let mut tasks = Vec::with_capacity(buckets.len());
+    for (i, &(bucket_start, bucket_end)) in buckets.iter().enumerate() {
+        let node = nodes[i % nodes.len()].clone();
+        let mut req = base_req.clone();
+        let req_query = req.query.as_mut().unwrap();
+        req_query.start = bucket_start;
+        req_query.end = bucket_end;
+        let trace_id = trace_id.to_string();
+        tasks.push(tokio::task::spawn(async move {
+            let resp = query_node(&node, req, &trace_id, probe_timeout).await?;
+            let scan_stats = resp.scan_stats.unwrap_or_default();
+            let cost = scan_stats.files as u64 + (scan_stats.original_size as u64 / (1024 * 1024));
+            Ok::<_, Error>(IntervalCost {
+                start: bucket_start,
+                end: bucket_end,
+                cost,
+            })
+        }));
+    }
+
+    match try_join_all(tasks).await {
+        Ok(results) => {
+            let mut costs = Vec::with_capacity(results.len());
+            for res in results {
+                match res {
+                    Ok(cost) => costs.push(cost),
+                    Err(err) => {
+                        log::warn!(
+                            "[trace_id {trace_id}] promql->search->partition: cost probe failed: {err:?}"
+                        );
+                        return None;
+                    }
+                }
+            }
+            Some(costs)
+        }
+        Err(err) => {
+            log::warn!("[trace_id {trace_id}] promql->search->partition: cost probe panicked: {err:?}");
+            None
+        }
+    }
+}
+
+/// Deterministic order used to resolve two samples landing on the same
+/// `(signature, timestamp)` (overlapping partitions, or WAL and storage both
+/// covering the same point), so the result doesn't depend on gRPC arrival
+/// order.
+///
+/// This does **not** implement the last-write-wins semantics ("greatest
+/// stamp wins, storage preferred over WAL, then higher node id") that
+/// motivated it: that needs a `stamp`/`source`/`node_id` extension on
+/// `cluster_rpc::Sample` and a `grpc::stamp_sample` call wherever a node
+/// reads a sample off WAL or storage, and neither the proto change nor that
+/// read path are part of this checkout, so those three fields can only ever
+/// be their zero defaults here. What this actually provides, today, is a
+/// tie-break on the sample's raw value bits -- arbitrary with respect to
+/// which value is "correct", but total and deterministic, so a collision
+/// resolves the same way regardless of which node's response arrives first.
+/// Land the proto change + stamping before claiming real LWW semantics.
+fn merge_tiebreak_rank(sample: &cluster_rpc::Sample) -> (i64, u8, u64, u64) {
+    (
+        sample.stamp,
+        source_priority(sample.source),
+        sample.node_id,
+        sample.value.to_bits(),
+    )
+}
+
+fn source_priority(source: i32) -> u8 {
+    if source == cluster_rpc::SampleSource::Storage as i32 {
+        2
+    } else if source == cluster_rpc::SampleSource::Wal as i32 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Keep `candidate` in `slot` only if it outranks whatever is already there,
+/// per [`merge_tiebreak_rank`].
+fn keep_highest_ranked(slot: &mut cluster_rpc::Sample, candidate: cluster_rpc::Sample) {
+    if merge_tiebreak_rank(&candidate) > merge_tiebreak_rank(slot) {
+        *slot = candidate;
+    }
+}
+
+fn merge_matrix_query(series: &[cluster_rpc::Series], scan_stats: &ScanStats) -> Value {
+    let threshold = get_config().limit.metrics_streaming_merge_threshold;
+    if scan_stats.original_size >= threshold {
+        merge_matrix_query_streaming(series)
+    } else {
+        merge_matrix_query_in_memory(series)
+    }
+}
+
+/// Materializes every sample into per-series maps before sorting. Simple and
+/// fine for small results, but holds the full result set in memory at once.
+fn merge_matrix_query_in_memory(series: &[cluster_rpc::Series]) -> Value {
+    let mut merged_data: HashMap<Signature, HashMap<i64, cluster_rpc::Sample>> = HashMap::new();
     let mut merged_metrics = HashMap::new();
     for ser in series {
         let labels: Labels = ser
@@ -344,7 +644,10 @@ fn merge_matrix_query(series: &[cluster_rpc::Series]) -> Value {
             .entry(signature(&labels))
             .or_insert_with(HashMap::new);
         ser.samples.iter().for_each(|v| {
-            entry.insert(v.time, v.value);
+            entry
+                .entry(v.time)
+                .and_modify(|slot| keep_highest_ranked(slot, v.clone()))
+                .or_insert_with(|| v.clone());
         });
         merged_metrics.insert(signature(&labels), labels);
     }
@@ -353,9 +656,9 @@ fn merge_matrix_query(series: &[cluster_rpc::Series]) -> Value {
         .map(|(sig, samples)| {
             let mut samples = samples
                 .into_iter()
-                .map(|(ts, v)| Sample {
-                    timestamp: ts,
-                    value: v,
+                .map(|(_, v)| Sample {
+                    timestamp: v.time,
+                    value: v.value,
                 })
                 .collect::<Vec<_>>();
             samples.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
@@ -368,8 +671,114 @@ fn merge_matrix_query(series: &[cluster_rpc::Series]) -> Value {
     value
 }
 
+/// Bounded-memory k-way merge: each node already returns a series' samples
+/// in time order, so we drive one cursor per (node, series) off a
+/// `BinaryHeap` keyed on `(signature, timestamp)` and pull one sample at a
+/// time. Keying on signature first means every sample for the current
+/// minimum signature pops before any sample of the next one, so a series'
+/// `RangeValue` can be emitted -- and its working set dropped -- as soon as
+/// that series is exhausted across every node, instead of holding the whole
+/// result in memory. Series with zero samples don't have anything to drive
+/// the heap with, so they're tracked separately and emitted with an empty
+/// `RangeValue` at the end, the same as
+/// [`merge_matrix_query_in_memory`] does.
+fn merge_matrix_query_streaming(series: &[cluster_rpc::Series]) -> Value {
+    struct Cursor<'a> {
+        signature: Signature,
+        pos: usize,
+        samples: &'a [cluster_rpc::Sample],
+    }
+
+    let mut labels_by_sig: HashMap<Signature, Labels> = HashMap::new();
+    let mut cursors: Vec<Cursor> = Vec::new();
+    for ser in series {
+        let labels: Labels = ser
+            .metric
+            .iter()
+            .map(|v| Arc::new(Label::from(v)))
+            .collect();
+        let sig = signature(&labels);
+        labels_by_sig.entry(sig).or_insert(labels);
+        // A series with no samples still needs a `RangeValue` in the output
+        // -- `merge_matrix_query_in_memory` always creates one per distinct
+        // signature -- but it has nothing to drive the heap with, so it's
+        // handled separately below instead of getting a cursor here.
+        if ser.samples.is_empty() {
+            continue;
+        }
+        cursors.push(Cursor {
+            signature: sig,
+            pos: 0,
+            samples: &ser.samples,
+        });
+    }
+
+    let mut heap: BinaryHeap<Reverse<(Signature, i64, usize)>> = BinaryHeap::new();
+    for (idx, cursor) in cursors.iter().enumerate() {
+        if let Some(s) = cursor.samples.first() {
+            heap.push(Reverse((cursor.signature, s.time, idx)));
+        }
+    }
+
+    let mut merged_data = Vec::new();
+    let mut current_sig: Option<Signature> = None;
+    let mut current_samples: HashMap<i64, cluster_rpc::Sample> = HashMap::new();
+    let flush = |sig: Signature,
+                 labels_by_sig: &HashMap<Signature, Labels>,
+                 samples: &mut HashMap<i64, cluster_rpc::Sample>| {
+        let mut samples = std::mem::take(samples)
+            .into_iter()
+            .map(|(_, v)| Sample {
+                timestamp: v.time,
+                value: v.value,
+            })
+            .collect::<Vec<_>>();
+        samples.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        RangeValue::new(labels_by_sig.get(&sig).unwrap().to_owned(), samples)
+    };
+
+    while let Some(Reverse((sig, _ts, idx))) = heap.pop() {
+        let sample = cursors[idx].samples[cursors[idx].pos].clone();
+        cursors[idx].pos += 1;
+        if let Some(next) = cursors[idx].samples.get(cursors[idx].pos) {
+            heap.push(Reverse((sig, next.time, idx)));
+        }
+
+        if current_sig != Some(sig) {
+            if let Some(prev_sig) = current_sig.replace(sig) {
+                merged_data.push(flush(prev_sig, &labels_by_sig, &mut current_samples));
+            }
+        }
+        current_samples
+            .entry(sample.time)
+            .and_modify(|slot| keep_highest_ranked(slot, sample.clone()))
+            .or_insert(sample);
+    }
+    if let Some(sig) = current_sig {
+        merged_data.push(flush(sig, &labels_by_sig, &mut current_samples));
+    }
+
+    // Signatures that only ever showed up in empty-sample series never got a
+    // cursor, so the heap walk above never emitted a `RangeValue` for them.
+    // Emit one now with no samples, so a series with zero samples on every
+    // node still shows up in the result here, matching
+    // `merge_matrix_query_in_memory` instead of silently vanishing depending
+    // on which merge path ran.
+    let emitted: std::collections::HashSet<Signature> =
+        cursors.iter().map(|c| c.signature).collect();
+    for (sig, labels) in &labels_by_sig {
+        if !emitted.contains(sig) {
+            merged_data.push(RangeValue::new(labels.to_owned(), Vec::new()));
+        }
+    }
+
+    let mut value = Value::Matrix(merged_data);
+    value.sort();
+    value
+}
+
 fn merge_vector_query(series: &[cluster_rpc::Series]) -> Value {
-    let mut merged_data = HashMap::new();
+    let mut merged_data: HashMap<Signature, cluster_rpc::Sample> = HashMap::new();
     let mut merged_metrics: HashMap<Signature, Vec<Arc<Label>>> = HashMap::new();
     for ser in series {
         let labels: Labels = ser
@@ -377,15 +786,18 @@ fn merge_vector_query(series: &[cluster_rpc::Series]) -> Value {
             .iter()
             .map(|l| Arc::new(Label::from(l)))
             .collect();
-        let sample: Sample = ser.sample.as_ref().unwrap().into();
-        merged_data.insert(signature(&labels), sample);
+        let sample = ser.sample.clone().unwrap();
+        merged_data
+            .entry(signature(&labels))
+            .and_modify(|slot| keep_highest_ranked(slot, sample.clone()))
+            .or_insert(sample);
         merged_metrics.insert(signature(&labels), labels);
     }
     let merged_data = merged_data
         .into_iter()
         .map(|(sig, sample)| InstantValue {
             labels: merged_metrics.get(&sig).unwrap().to_owned(),
-            sample,
+            sample: (&sample).into(),
         })
         .collect::<Vec<_>>();
 
@@ -395,13 +807,26 @@ fn merge_vector_query(series: &[cluster_rpc::Series]) -> Value {
 }
 
 fn merge_scalar_query(series: &[cluster_rpc::Series]) -> Value {
-    let mut sample: Sample = Default::default();
+    let mut winner: Option<cluster_rpc::Sample> = None;
+    let mut scalar_fallback: Option<f64> = None;
     for ser in series {
         if let Some(x) = ser.sample.as_ref() {
-            sample = x.into();
+            match &mut winner {
+                Some(slot) => keep_highest_ranked(slot, x.clone()),
+                None => winner = Some(x.clone()),
+            }
         } else if let Some(x) = ser.scalar {
-            sample.value = x;
+            // Plain scalars carry no LWW metadata; keep the last one seen,
+            // matching the historical behavior for this (legacy) path.
+            scalar_fallback = Some(x);
         }
     }
+    let sample = match winner {
+        Some(s) => (&s).into(),
+        None => Sample {
+            value: scalar_fallback.unwrap_or_default(),
+            ..Default::default()
+        },
+    };
     Value::Sample(sample)
 }
@@ -0,0 +1,137 @@
+// Copyright 2024 OpenObserve Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Helpers for splitting a `[start, end]` query range across queriers.
+//!
+//! The default strategy (see [`equal_slices`]) just divides the range into
+//! `nr_queriers` equal slices. When data volume is skewed across time (e.g. a
+//! traffic spike in one hour of a day-long range), that leaves some queriers
+//! idle while one straggler scans a dense interval alone. [`adaptive_slices`]
+//! fixes that by accepting a per-sub-interval cost estimate (file count /
+//! series count / byte size -- anything comparable) and placing boundaries
+//! so each querier gets roughly the same share of total cost.
+
+/// A cheap estimate of how expensive it is to scan `[start, end)`.
+///
+/// `cost` is unitless and only meaningful relative to other `IntervalCost`s
+/// from the same pre-pass (e.g. summed `ScanStats::files` or
+/// `ScanStats::original_size`).
+#[derive(Debug, Clone, Copy)]
+pub(super) struct IntervalCost {
+    pub start: i64,
+    pub end: i64,
+    pub cost: u64,
+}
+
+/// Divide `[start, end)` into at most `nr_queriers` equal slices of
+/// `worker_dt`, snapped to `partition_step`; the last slice ends at `end` and
+/// may be shorter. An empty range (`start == end`) yields the single slice
+/// `(start, end)`. This is the historical behavior and the fallback used
+/// whenever a cost pre-pass isn't available.
+pub(super) fn equal_slices(start: i64, end: i64, partition_step: i64, nr_queriers: i64) -> Vec<(i64, i64)> {
+    let nr_steps = match (end - start + partition_step - 1) / partition_step {
+        0 => 1,
+        n => n,
+    };
+    let worker_dt = if nr_steps > nr_queriers {
+        partition_step * ((nr_steps + nr_queriers - 1) / nr_queriers)
+    } else {
+        partition_step
+    };
+    let mut slices = Vec::new();
+    let mut worker_start = start;
+    while worker_start < end {
+        let worker_end = std::cmp::min(end, worker_start + worker_dt);
+        slices.push((worker_start, worker_end));
+        worker_start += worker_dt;
+    }
+    if slices.is_empty() {
+        slices.push((start, end));
+    }
+    slices
+}
+
+/// Place up to `nr_queriers` partition boundaries over `[start, end)` so that
+/// each partition covers roughly `total_cost / nr_queriers` of the work
+/// described by `costs`, greedily walking the cumulative cost curve and
+/// snapping every boundary to a multiple of `partition_step` so partitions
+/// stay aligned with the file/wal retention rules the caller applies
+/// afterwards.
+///
+/// `costs` must be sorted by `start` and cover `[start, end)` without gaps;
+/// if it's empty (pre-pass unavailable) or sums to zero cost, this falls
+/// back to [`equal_slices`].
+pub(super) fn adaptive_slices(
+    start: i64,
+    end: i64,
+    partition_step: i64,
+    nr_queriers: i64,
+    costs: &[IntervalCost],
+) -> Vec<(i64, i64)> {
+    let total_cost: u128 = costs.iter().map(|c| c.cost as u128).sum();
+    if costs.is_empty() || total_cost == 0 || nr_queriers <= 1 || start >= end {
+        return equal_slices(start, end, partition_step, nr_queriers);
+    }
+
+    let target = total_cost as f64 / nr_queriers as f64;
+    let mut boundaries = Vec::with_capacity(nr_queriers as usize + 1);
+    boundaries.push(start);
+
+    let mut cumulative = 0u128;
+    let mut next_target = target;
+    for c in costs {
+        if c.cost == 0 {
+            continue;
+        }
+        let interval_start_cum = cumulative as f64;
+        cumulative += c.cost as u128;
+        let interval_end_cum = cumulative as f64;
+
+        // Place every boundary that falls inside this interval's cost range.
+        while next_target <= interval_end_cum && boundaries.len() < nr_queriers as usize {
+            // Interpolate the boundary position within [c.start, c.end]
+            // proportional to how far into this interval's cost we are.
+            let frac = ((next_target - interval_start_cum) / (c.cost as f64)).clamp(0.0, 1.0);
+            let raw = c.start + ((c.end - c.start) as f64 * frac).round() as i64;
+            // Snapping can round a boundary past `end` (e.g. the last
+            // interval's raw position rounds up to the next step past the
+            // range); clamp it back so we never push a boundary beyond what
+            // the final `end` push below produces.
+            let snapped = snap_to_step(raw, start, partition_step).min(end);
+            let prev = *boundaries.last().unwrap();
+            // Never produce an empty or backwards partition.
+            if snapped > prev {
+                boundaries.push(snapped);
+            }
+            next_target += target;
+        }
+    }
+    if *boundaries.last().unwrap() != end {
+        boundaries.push(end);
+    }
+    boundaries.dedup();
+
+    boundaries.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+/// Snap `ts` to the nearest multiple of `partition_step` relative to
+/// `origin`; for `ts >= origin` the result is never below `origin`.
+fn snap_to_step(ts: i64, origin: i64, partition_step: i64) -> i64 {
+    if partition_step <= 0 {
+        return ts;
+    }
+    let steps = ((ts - origin) as f64 / partition_step as f64).round() as i64;
+    origin + steps * partition_step
+}